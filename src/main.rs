@@ -1,11 +1,16 @@
 use std::io::Write;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 use std::{env, fs, process};
 
 use chrono::Local;
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use serde::Deserialize;
 use serde_json::{Map, Value};
+use s3::creds::Credentials;
+use s3::{Bucket, Region};
+use tokio::sync::Semaphore;
 
 /// Generate images with the Ideogram v3 API.
 ///
@@ -52,6 +57,93 @@ struct Cli {
     /// Path to a character reference image (JPEG, PNG, or WebP; max 10MB)
     #[arg(long)]
     character_ref: Option<String>,
+
+    /// Maximum number of images to download in parallel
+    #[arg(long, default_value_t = 4)]
+    concurrency: usize,
+
+    /// Number of retry attempts per image download before giving up
+    #[arg(long, default_value_t = 3)]
+    retries: u32,
+
+    /// Output image format to transcode to
+    #[arg(long, value_enum, default_value_t = Format::Png)]
+    format: Format,
+
+    /// Encoding quality (1–100) for lossy formats
+    #[arg(long, default_value_t = 90)]
+    quality: u8,
+
+    /// S3-compatible bucket to upload generated images to
+    #[arg(long)]
+    s3_bucket: Option<String>,
+
+    /// Custom S3 endpoint (e.g. https://nyc3.digitaloceanspaces.com)
+    #[arg(long)]
+    s3_endpoint: Option<String>,
+
+    /// S3 region
+    #[arg(long, default_value = "us-east-1")]
+    s3_region: String,
+
+    /// Render each generated image inline in the terminal
+    #[arg(long)]
+    preview: bool,
+
+    /// Print a BlurHash placeholder string for each image (to stderr)
+    #[arg(long)]
+    blurhash: bool,
+
+    /// Upload images to a pict-rs instance at this base URL
+    #[arg(long)]
+    pictrs_url: Option<String>,
+
+    /// Post the generated images to a Mastodon account as a status
+    #[arg(long)]
+    post_mastodon: bool,
+
+    /// Mastodon instance base URL (e.g. https://mastodon.social)
+    #[arg(long)]
+    mastodon_instance: Option<String>,
+
+    /// Alt text for the posted media (defaults to the prompt)
+    #[arg(long)]
+    alt: Option<String>,
+
+    /// Status body for the Mastodon post
+    #[arg(long, default_value = "")]
+    caption: String,
+}
+
+/// Output image formats supported by the transcode stage.
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Png,
+    Jpeg,
+    Webp,
+    Avif,
+}
+
+impl Format {
+    /// File extension (without the dot) for this format.
+    fn ext(self) -> &'static str {
+        match self {
+            Format::Png => "png",
+            Format::Jpeg => "jpg",
+            Format::Webp => "webp",
+            Format::Avif => "avif",
+        }
+    }
+
+    /// MIME content type for this format.
+    fn content_type(self) -> &'static str {
+        match self {
+            Format::Png => "image/png",
+            Format::Jpeg => "image/jpeg",
+            Format::Webp => "image/webp",
+            Format::Avif => "image/avif",
+        }
+    }
 }
 
 #[derive(Deserialize)]
@@ -64,7 +156,58 @@ struct ImageData {
     url: String,
 }
 
-fn main() {
+#[derive(Deserialize)]
+struct PictrsResponse {
+    files: Vec<PictrsFile>,
+}
+
+#[derive(Deserialize)]
+struct PictrsFile {
+    file: String,
+    delete_token: Option<String>,
+}
+
+/// Download a single URL, retrying transient failures with exponential backoff.
+///
+/// Returns the image bytes on success, or the last error message after all
+/// attempts are exhausted. A failed download aborts only this image, never the
+/// whole process.
+async fn download_with_retry(
+    client: &reqwest::Client,
+    url: &str,
+    retries: u32,
+) -> Result<Vec<u8>, String> {
+    let mut attempt = 0;
+    loop {
+        let result = async {
+            let resp = client.get(url).send().await.map_err(|e| e.to_string())?;
+            let resp = resp.error_for_status().map_err(|e| e.to_string())?;
+            resp.bytes().await.map(|b| b.to_vec()).map_err(|e| e.to_string())
+        }
+        .await;
+
+        match result {
+            Ok(bytes) => return Ok(bytes),
+            Err(e) if attempt < retries => {
+                // Exponential backoff: 500ms, 1s, 2s, … capped so a large
+                // `--retries` can't overflow the shift (and the delay) and panic.
+                let delay = Duration::from_millis(500u64 << attempt.min(6));
+                eprintln!(
+                    "Warning: download of {url} failed (attempt {}/{}): {e}; retrying in {:?}",
+                    attempt + 1,
+                    retries + 1,
+                    delay
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
     let cli = Cli::parse();
 
     let api_key = env::var("IDEOGRAM_API_KEY").unwrap_or_else(|_| {
@@ -104,7 +247,7 @@ fn main() {
 
     eprintln!("Generating image...");
 
-    let client = reqwest::blocking::Client::new();
+    let client = reqwest::Client::new();
 
     // Call API — use multipart when a character reference image is provided,
     // otherwise use a plain JSON body.
@@ -114,13 +257,13 @@ fn main() {
             .unwrap_or_default()
             .to_string_lossy()
             .to_string();
-        let file_part = reqwest::blocking::multipart::Part::bytes(bytes)
+        let file_part = reqwest::multipart::Part::bytes(bytes)
             .file_name(filename)
             .mime_str(mime)
             .unwrap();
 
-        let mut form = reqwest::blocking::multipart::Form::new()
-            .text("prompt", cli.prompt)
+        let mut form = reqwest::multipart::Form::new()
+            .text("prompt", cli.prompt.clone())
             .text("aspect_ratio", cli.aspect)
             .text("rendering_speed", cli.speed)
             .text("num_images", cli.num.to_string())
@@ -144,13 +287,14 @@ fn main() {
             .header("Api-Key", &api_key)
             .multipart(form)
             .send()
+            .await
             .unwrap_or_else(|e| {
                 eprintln!("Error: request failed: {e}");
                 process::exit(1);
             })
     } else {
         let mut body = Map::new();
-        body.insert("prompt".into(), Value::String(cli.prompt));
+        body.insert("prompt".into(), Value::String(cli.prompt.clone()));
         body.insert("aspect_ratio".into(), Value::String(cli.aspect));
         body.insert("rendering_speed".into(), Value::String(cli.speed));
         body.insert("num_images".into(), Value::Number(cli.num.into()));
@@ -173,6 +317,7 @@ fn main() {
             .header("Api-Key", &api_key)
             .json(&body)
             .send()
+            .await
             .unwrap_or_else(|e| {
                 eprintln!("Error: request failed: {e}");
                 process::exit(1);
@@ -181,7 +326,7 @@ fn main() {
 
     let status = response.status();
     if !status.is_success() {
-        let text = response.text().unwrap_or_default();
+        let text = response.text().await.unwrap_or_default();
         eprintln!("Error: API returned HTTP {status}");
         // Try to pretty-print the error JSON
         if let Ok(json) = serde_json::from_str::<Value>(&text) {
@@ -192,61 +337,611 @@ fn main() {
         process::exit(1);
     }
 
-    let api_response: ApiResponse = response.json().unwrap_or_else(|e| {
+    let api_response: ApiResponse = response.json().await.unwrap_or_else(|e| {
         eprintln!("Error: failed to parse API response: {e}");
         process::exit(1);
     });
 
     let image_count = api_response.data.len();
 
-    // Download images
-    for (i, image) in api_response.data.iter().enumerate() {
-        let dest = match &cli.output {
-            Some(output) if image_count == 1 => PathBuf::from(output),
+    // Resolve the destination path for each image up front so concurrent
+    // downloads can't race on the timestamp.
+    let ts = Local::now().format("%Y%m%d_%H%M%S").to_string();
+    let dests: Vec<PathBuf> = api_response
+        .data
+        .iter()
+        .enumerate()
+        .map(|(i, _)| match &cli.output {
+            Some(output) if image_count == 1 => {
+                let path = PathBuf::from(output);
+                let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                let parent = path.parent().unwrap_or(std::path::Path::new(""));
+                parent.join(format!("{stem}.{ext}", ext = cli.format.ext()))
+            }
             Some(output) => {
                 let path = PathBuf::from(output);
                 let stem = path.file_stem().unwrap_or_default().to_string_lossy();
-                let ext = path.extension().unwrap_or_default().to_string_lossy();
                 let parent = path.parent().unwrap_or(std::path::Path::new(""));
-                parent.join(format!("{stem}_{}.{ext}", i + 1))
+                parent.join(format!("{stem}_{}.{ext}", i + 1, ext = cli.format.ext()))
             }
-            None => {
-                let ts = Local::now().format("%Y%m%d_%H%M%S");
-                if image_count == 1 {
-                    PathBuf::from(format!("ideo_{ts}.png"))
-                } else {
-                    PathBuf::from(format!("ideo_{ts}_{}.png", i + 1))
+            None if image_count == 1 => PathBuf::from(format!("ideo_{ts}.{ext}", ext = cli.format.ext())),
+            None => PathBuf::from(format!("ideo_{ts}_{}.{ext}", i + 1, ext = cli.format.ext())),
+        })
+        .collect();
+
+    // When an S3 bucket is configured, build the client and per-image object
+    // keys up front (from the timestamp and prompt slug).
+    let slug = slugify(&cli.prompt);
+    let bucket = cli.s3_bucket.as_ref().map(|name| {
+        let region = match &cli.s3_endpoint {
+            Some(endpoint) => Region::Custom {
+                region: cli.s3_region.clone(),
+                endpoint: endpoint.clone(),
+            },
+            None => cli.s3_region.parse().unwrap_or(Region::UsEast1),
+        };
+        let creds = Credentials::from_env().unwrap_or_else(|e| {
+            eprintln!("Error: missing AWS credentials in environment: {e}");
+            process::exit(1);
+        });
+        let mut bucket = *Bucket::new(name, region, creds).unwrap_or_else(|e| {
+            eprintln!("Error: could not open S3 bucket {name}: {e}");
+            process::exit(1);
+        });
+        // Custom endpoints (MinIO, etc.) are path-style; keep PUT target and the
+        // printed URL (see `object_url`) in the same addressing mode.
+        if cli.s3_endpoint.is_some() {
+            bucket.set_path_style();
+        }
+        Arc::new(bucket)
+    });
+    let pictrs_api_key = env::var("PICTRS_API_KEY").ok();
+    let keys: Vec<String> = (0..image_count)
+        .map(|i| {
+            if image_count == 1 {
+                format!("{ts}-{slug}.{ext}", ext = cli.format.ext())
+            } else {
+                format!("{ts}-{slug}-{}.{ext}", i + 1, ext = cli.format.ext())
+            }
+        })
+        .collect();
+
+    // Download every image concurrently, bounded by a semaphore.
+    let semaphore = Arc::new(Semaphore::new(cli.concurrency.max(1)));
+    let mut handles = Vec::with_capacity(image_count);
+    for ((image, dest), key) in api_response.data.iter().zip(dests.iter()).zip(keys.iter()) {
+        let client = client.clone();
+        let semaphore = Arc::clone(&semaphore);
+        let url = image.url.clone();
+        let dest = dest.clone();
+        let key = key.clone();
+        let retries = cli.retries;
+        let format = cli.format;
+        let quality = cli.quality;
+        let bucket = bucket.clone();
+        let preview = cli.preview;
+        let blurhash = cli.blurhash;
+        let pictrs_url = cli.pictrs_url.clone();
+        let pictrs_api_key = pictrs_api_key.clone();
+        let s3_endpoint = cli.s3_endpoint.clone();
+        let s3_region = cli.s3_region.clone();
+        let s3_bucket = cli.s3_bucket.clone();
+        handles.push(tokio::spawn(async move {
+            let _permit = semaphore.acquire().await.expect("semaphore closed");
+            let bytes = Arc::new(download_with_retry(&client, &url, retries).await?);
+
+            // blurhash and transcode are CPU-bound (full-image DCT, decode/
+            // encode); run them on the blocking pool so they don't stall the
+            // async workers driving the other concurrent downloads.
+            let hash = if blurhash {
+                let bytes = Arc::clone(&bytes);
+                let res = tokio::task::spawn_blocking(move || blurhash_encode(&bytes, 4, 3))
+                    .await
+                    .map_err(|e| e.to_string())?;
+                match res {
+                    Ok(h) => Some(h),
+                    Err(e) => {
+                        eprintln!("Warning: could not compute blurhash: {e}");
+                        None
+                    }
+                }
+            } else {
+                None
+            };
+
+            let encoded = {
+                let bytes = Arc::clone(&bytes);
+                tokio::task::spawn_blocking(move || transcode(&bytes, format, quality))
+                    .await
+                    .map_err(|e| e.to_string())??
+            };
+            write_image(&dest, &encoded)?;
+
+            if preview {
+                if let Err(e) = preview_image(&dest) {
+                    eprintln!("Warning: could not preview {}: {e}", dest.display());
                 }
             }
+
+            // Optional uploads. pict-rs takes precedence for the stdout line
+            // when more than one target is configured.
+            let mut uploaded: Option<String> = None;
+
+            if let Some(base) = &pictrs_url {
+                let url = upload_pictrs(
+                    &client,
+                    base,
+                    pictrs_api_key.as_deref(),
+                    &key,
+                    &encoded,
+                    format,
+                )
+                .await?;
+                uploaded = Some(url);
+            }
+
+            if let Some(bucket) = &bucket {
+                bucket
+                    .put_object_with_content_type(&key, &encoded, format.content_type())
+                    .await
+                    .map_err(|e| format!("failed to upload {key} to S3: {e}"))?;
+                let s3_url = object_url(
+                    &s3_endpoint,
+                    &s3_region,
+                    s3_bucket.as_deref().unwrap_or_default(),
+                    &key,
+                );
+                uploaded.get_or_insert(s3_url);
+            }
+
+            Ok::<_, String>((dest, uploaded, hash))
+        }));
+    }
+
+    let mut succeeded = 0usize;
+    let mut failed = 0usize;
+    let mut saved = Vec::with_capacity(image_count);
+    for handle in handles {
+        match handle.await.expect("download task panicked") {
+            Ok((dest, uploaded, hash)) => {
+                succeeded += 1;
+                eprintln!("Saved: {}", dest.display());
+                if let Some(hash) = hash {
+                    eprintln!("BlurHash: {hash}");
+                }
+                match uploaded {
+                    Some(url) => println!("{url}"),
+                    None => println!("{}", dest.display()),
+                }
+                saved.push(dest);
+            }
+            Err(e) => {
+                failed += 1;
+                eprintln!("Error: {e}");
+            }
+        }
+    }
+
+    eprintln!("Downloaded {succeeded}/{image_count} images ({failed} failed)");
+
+    if cli.post_mastodon && !saved.is_empty() {
+        let instance = cli.mastodon_instance.unwrap_or_else(|| {
+            eprintln!("Error: --mastodon-instance is required with --post-mastodon");
+            process::exit(1);
+        });
+        let token = env::var("MASTODON_TOKEN").unwrap_or_else(|_| {
+            eprintln!("Error: MASTODON_TOKEN environment variable is not set");
+            process::exit(1);
+        });
+        let alt = cli.alt.unwrap_or(cli.prompt);
+        if let Err(e) = post_mastodon(&instance, token, &saved, &alt, &cli.caption).await {
+            eprintln!("Error: failed to post to Mastodon: {e}");
+        }
+    }
+
+    if succeeded == 0 && image_count > 0 {
+        process::exit(1);
+    }
+}
+
+/// Publish the generated images to a Mastodon account as a single status.
+///
+/// Each image is uploaded as media with `alt` as its description, then a status
+/// with `caption` is posted referencing the media. The resulting post URL is
+/// printed to stderr.
+async fn post_mastodon(
+    instance: &str,
+    token: String,
+    paths: &[PathBuf],
+    alt: &str,
+    caption: &str,
+) -> Result<(), String> {
+    use megalodon::megalodon::{PostStatusInputOptions, UploadMediaInputOptions};
+
+    let client =
+        megalodon::generator(megalodon::SNS::Mastodon, instance.to_string(), Some(token), None)
+            .map_err(|e| e.to_string())?;
+
+    let mut media_ids = Vec::with_capacity(paths.len());
+    for path in paths {
+        let options = UploadMediaInputOptions {
+            description: Some(alt.to_string()),
+            ..Default::default()
+        };
+        let res = client
+            .upload_media(path.to_string_lossy().to_string(), Some(&options))
+            .await
+            .map_err(|e| e.to_string())?;
+        let id = match res.json {
+            megalodon::entities::UploadMedia::Attachment(a) => a.id,
+            megalodon::entities::UploadMedia::AsyncAttachment(a) => a.id,
         };
+        media_ids.push(id);
+    }
 
-        // Create parent directories if needed
-        if let Some(parent) = dest.parent() {
-            if !parent.as_os_str().is_empty() {
-                fs::create_dir_all(parent).unwrap_or_else(|e| {
-                    eprintln!("Error: could not create directory {}: {e}", parent.display());
-                    process::exit(1);
-                });
+    let options = PostStatusInputOptions {
+        media_ids: Some(media_ids),
+        ..Default::default()
+    };
+    let res = client
+        .post_status(caption.to_string(), Some(&options))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if let megalodon::entities::PostStatusOutput::Status(status) = res.json {
+        if let Some(url) = status.url {
+            eprintln!("Posted to Mastodon: {url}");
+        }
+    }
+    Ok(())
+}
+
+/// Upload a single image to a pict-rs instance and return its canonical URL.
+///
+/// Posts a multipart form to `<base>/image`, parses the returned file
+/// identifier, and prints the delete token to stderr so the upload can be
+/// purged later.
+async fn upload_pictrs(
+    client: &reqwest::Client,
+    base: &str,
+    api_key: Option<&str>,
+    filename: &str,
+    bytes: &[u8],
+    format: Format,
+) -> Result<String, String> {
+    let base = base.trim_end_matches('/');
+    let part = reqwest::multipart::Part::bytes(bytes.to_vec())
+        .file_name(filename.to_string())
+        .mime_str(format.content_type())
+        .map_err(|e| e.to_string())?;
+    let form = reqwest::multipart::Form::new().part("images[]", part);
+
+    let mut req = client.post(format!("{base}/image")).multipart(form);
+    if let Some(key) = api_key {
+        req = req.header("X-Api-Token", key);
+    }
+
+    let resp = req
+        .send()
+        .await
+        .map_err(|e| format!("pict-rs request failed: {e}"))?
+        .error_for_status()
+        .map_err(|e| format!("pict-rs returned error: {e}"))?;
+    let parsed: PictrsResponse = resp
+        .json()
+        .await
+        .map_err(|e| format!("failed to parse pict-rs response: {e}"))?;
+
+    let file = parsed
+        .files
+        .into_iter()
+        .next()
+        .ok_or_else(|| "pict-rs response contained no files".to_string())?;
+    if let Some(token) = &file.delete_token {
+        eprintln!("Delete token for {}: {token}", file.file);
+    }
+    Ok(format!("{base}/image/original/{}", file.file))
+}
+
+/// Turn a prompt into a filesystem/URL-safe slug: lowercase, alphanumerics kept,
+/// everything else collapsed to single dashes, trimmed and capped in length.
+fn slugify(prompt: &str) -> String {
+    let mut slug = String::new();
+    let mut prev_dash = true; // avoids a leading dash
+    for ch in prompt.chars() {
+        if ch.is_ascii_alphanumeric() {
+            slug.push(ch.to_ascii_lowercase());
+            prev_dash = false;
+        } else if !prev_dash {
+            slug.push('-');
+            prev_dash = true;
+        }
+        if slug.len() >= 48 {
+            break;
+        }
+    }
+    let slug = slug.trim_matches('-').to_string();
+    if slug.is_empty() {
+        "image".to_string()
+    } else {
+        slug
+    }
+}
+
+const BASE83: &[u8; 83] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~";
+
+/// Encode `value` as a fixed-width base-83 string (most-significant digit first).
+fn base83_encode(value: usize, length: usize) -> String {
+    let mut out = String::with_capacity(length);
+    for i in 1..=length {
+        let digit = (value / 83usize.pow((length - i) as u32)) % 83;
+        out.push(BASE83[digit] as char);
+    }
+    out
+}
+
+fn srgb_to_linear(c: u8) -> f64 {
+    let v = c as f64 / 255.0;
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(v: f64) -> u32 {
+    let v = v.clamp(0.0, 1.0);
+    if v <= 0.0031308 {
+        (v * 12.92 * 255.0 + 0.5) as u32
+    } else {
+        ((1.055 * v.powf(1.0 / 2.4) - 0.055) * 255.0 + 0.5) as u32
+    }
+}
+
+/// Signed power used when quantising AC coefficients.
+fn sign_pow(value: f64, exp: f64) -> f64 {
+    value.signum() * value.abs().powf(exp)
+}
+
+/// Compute a BlurHash string for the given encoded image bytes.
+///
+/// Self-contained implementation following the reference algorithm: decode to
+/// RGB, convert each pixel to linear light, project onto the `cx`×`cy` DCT-style
+/// basis, then pack the DC and quantised AC coefficients into base-83.
+fn blurhash_encode(bytes: &[u8], cx: usize, cy: usize) -> Result<String, String> {
+    let cx = cx.clamp(1, 9);
+    let cy = cy.clamp(1, 9);
+
+    let img = image::load_from_memory(bytes)
+        .map_err(|e| format!("failed to decode image: {e}"))?
+        .to_rgb8();
+    let (width, height) = img.dimensions();
+    let (width, height) = (width as usize, height as usize);
+
+    // Project onto each basis function, accumulating a linear-RGB factor.
+    let mut factors: Vec<[f64; 3]> = Vec::with_capacity(cx * cy);
+    for j in 0..cy {
+        for i in 0..cx {
+            let normalisation = if i == 0 && j == 0 { 1.0 } else { 2.0 };
+            let mut factor = [0.0f64; 3];
+            for y in 0..height {
+                for x in 0..width {
+                    let basis = (std::f64::consts::PI * i as f64 * x as f64 / width as f64).cos()
+                        * (std::f64::consts::PI * j as f64 * y as f64 / height as f64).cos();
+                    let px = img.get_pixel(x as u32, y as u32);
+                    factor[0] += basis * srgb_to_linear(px[0]);
+                    factor[1] += basis * srgb_to_linear(px[1]);
+                    factor[2] += basis * srgb_to_linear(px[2]);
+                }
             }
+            let scale = normalisation / (width * height) as f64;
+            factors.push([factor[0] * scale, factor[1] * scale, factor[2] * scale]);
         }
+    }
 
-        let bytes = client
-            .get(&image.url)
-            .send()
-            .and_then(|r| r.bytes())
-            .unwrap_or_else(|e| {
-                eprintln!("Error: failed to download image: {e}");
-                process::exit(1);
-            });
+    let dc = factors[0];
+    let ac = &factors[1..];
 
-        fs::File::create(&dest)
-            .and_then(|mut f| f.write_all(&bytes))
-            .unwrap_or_else(|e| {
-                eprintln!("Error: failed to write {}: {e}", dest.display());
-                process::exit(1);
-            });
+    let mut hash = String::new();
+    let size_flag = (cx - 1) + (cy - 1) * 9;
+    hash.push_str(&base83_encode(size_flag, 1));
+
+    let maximum;
+    if ac.is_empty() {
+        maximum = 1.0;
+        hash.push_str(&base83_encode(0, 1));
+    } else {
+        let actual_max = ac
+            .iter()
+            .flat_map(|c| c.iter().copied())
+            .fold(0.0f64, |m, v| m.max(v.abs()));
+        let quantised_max = ((actual_max * 166.0 - 0.5).floor() as i64).clamp(0, 82) as usize;
+        maximum = (quantised_max + 1) as f64 / 166.0;
+        hash.push_str(&base83_encode(quantised_max, 1));
+    }
+
+    let encode_dc = (linear_to_srgb(dc[0]) << 16) + (linear_to_srgb(dc[1]) << 8) + linear_to_srgb(dc[2]);
+    hash.push_str(&base83_encode(encode_dc as usize, 4));
+
+    for c in ac {
+        let quant = |v: f64| -> usize {
+            ((sign_pow(v / maximum, 0.5) * 9.0 + 9.5).floor() as i64).clamp(0, 18) as usize
+        };
+        let value = quant(c[0]) * 19 * 19 + quant(c[1]) * 19 + quant(c[2]);
+        hash.push_str(&base83_encode(value, 2));
+    }
+
+    Ok(hash)
+}
+
+/// Render an image inline in the terminal.
+///
+/// Both the status line and the rendering go to stderr so stdout stays
+/// path-only. The image is scaled to the current terminal size (via
+/// `terminal_size`) and drawn with upper-half-block glyphs — two stacked
+/// pixels per character cell, truecolor foreground/background.
+fn preview_image(path: &std::path::Path) -> Result<(), String> {
+    eprintln!("Preview: {}", path.display());
+
+    // Render to stderr ourselves: viuer only writes to stdout, which would
+    // pollute the one-path-per-line contract. Each terminal cell is two
+    // stacked pixels drawn with the upper-half-block glyph — truecolor
+    // foreground for the top pixel, background for the bottom.
+    let img = image::open(path).map_err(|e| format!("failed to decode image: {e}"))?;
+
+    let (cols, rows) = terminal_size::terminal_size()
+        .map(|(w, h)| (w.0 as u32, h.0 as u32))
+        .unwrap_or((80, 24));
+    // Leave headroom for the shell prompt; two pixels per row cell.
+    let max_w = cols.max(1);
+    let max_h = (rows.saturating_sub(2)).max(1) * 2;
+    let img = img
+        .resize(max_w, max_h, image::imageops::FilterType::Triangle)
+        .to_rgba8();
+    let (w, h) = img.dimensions();
+
+    let stderr = std::io::stderr();
+    let mut out = stderr.lock();
+    for y in (0..h).step_by(2) {
+        for x in 0..w {
+            let top = img.get_pixel(x, y);
+            let bottom = if y + 1 < h {
+                *img.get_pixel(x, y + 1)
+            } else {
+                *top
+            };
+            write!(
+                out,
+                "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m▀",
+                top[0], top[1], top[2], bottom[0], bottom[1], bottom[2]
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        writeln!(out, "\x1b[0m").map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Build the public object URL for an uploaded key.
+fn object_url(endpoint: &Option<String>, region: &str, bucket: &str, key: &str) -> String {
+    match endpoint {
+        Some(endpoint) => format!("{}/{bucket}/{key}", endpoint.trim_end_matches('/')),
+        None => format!("https://{bucket}.s3.{region}.amazonaws.com/{key}"),
+    }
+}
+
+/// Decode the downloaded PNG bytes and re-encode them to the requested format.
+///
+/// `quality` is clamped to 1–100 and applies to the lossy formats (JPEG, WebP,
+/// AVIF); it is ignored for PNG.
+fn transcode(bytes: &[u8], format: Format, quality: u8) -> Result<Vec<u8>, String> {
+    use std::io::Cursor;
+
+    // PNG is what Ideogram already returns, so pass the original bytes through
+    // untouched rather than round-tripping through a decode/encode cycle.
+    if format == Format::Png {
+        return Ok(bytes.to_vec());
+    }
+
+    let img = image::load_from_memory(bytes).map_err(|e| format!("failed to decode image: {e}"))?;
+    let quality = quality.clamp(1, 100);
+
+    let mut out = Vec::new();
+    match format {
+        Format::Png => img
+            .write_to(&mut Cursor::new(&mut out), image::ImageFormat::Png)
+            .map_err(|e| format!("failed to encode PNG: {e}"))?,
+        Format::Jpeg => {
+            let encoder =
+                image::codecs::jpeg::JpegEncoder::new_with_quality(&mut out, quality);
+            img.write_with_encoder(encoder)
+                .map_err(|e| format!("failed to encode JPEG: {e}"))?;
+        }
+        Format::Webp => {
+            let encoder = webp::Encoder::from_image(&img)
+                .map_err(|e| format!("failed to encode WebP: {e}"))?;
+            out = encoder.encode(quality as f32).to_vec();
+        }
+        Format::Avif => {
+            let encoder =
+                image::codecs::avif::AvifEncoder::new_with_speed_quality(&mut out, 5, quality);
+            img.write_with_encoder(encoder)
+                .map_err(|e| format!("failed to encode AVIF: {e}"))?;
+        }
+    }
+    Ok(out)
+}
+
+/// Write image bytes to `dest`, creating parent directories as needed.
+fn write_image(dest: &std::path::Path, bytes: &[u8]) -> Result<(), String> {
+    if let Some(parent) = dest.parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("could not create directory {}: {e}", parent.display()))?;
+        }
+    }
+    fs::File::create(dest)
+        .and_then(|mut f| f.write_all(bytes))
+        .map_err(|e| format!("failed to write {}: {e}", dest.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// A solid-colour image gives a stable known vector we can assert against
+    /// without an external reference implementation. Its AC energy is not
+    /// zero: with the `cos(π·i·x/width)` basis, the samples of an odd component
+    /// sum to 1 rather than 0, so (1,0), (3,0) and (0,1) come out at 0.25 and
+    /// (1,1), (3,1) at 0.03125 on an 8×8 image; every other AC term is 0.
+    #[test]
+    fn blurhash_solid_white() {
+        let img = image::RgbImage::from_pixel(8, 8, image::Rgb([255, 255, 255]));
+        let mut png = Vec::new();
+        image::DynamicImage::ImageRgb8(img)
+            .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+
+        let hash = blurhash_encode(&png, 4, 3).unwrap();
+        // size flag 'L' (4×3), quantised max 'f' (41, from the 0.25 peak),
+        // DC "TSUA" (white), then the 11 AC coefficients in row-major order:
+        // "~q" for 0.25, "t7" for 0.03125 and "fQ" for 0.
+        assert_eq!(hash, "LfTSUA~qfQ~q~qt7fQt7fQfQfQfQ");
+    }
+
+    #[test]
+    fn base83_round_values() {
+        assert_eq!(base83_encode(0, 1), "0");
+        assert_eq!(base83_encode(82, 1), "~");
+        assert_eq!(base83_encode(16777215, 4), "TSUA");
+    }
+
+    #[test]
+    fn slugify_cases() {
+        assert_eq!(slugify("Hello, World!"), "hello-world");
+        assert_eq!(slugify("  a   b  "), "a-b");
+        assert_eq!(slugify("!!!"), "image");
+        assert_eq!(slugify(""), "image");
+    }
 
-        eprintln!("Saved: {}", dest.display());
-        println!("{}", dest.display());
+    #[test]
+    fn object_url_addressing_modes() {
+        // Virtual-hosted for AWS (no custom endpoint).
+        assert_eq!(
+            object_url(&None, "us-west-2", "mybucket", "k.png"),
+            "https://mybucket.s3.us-west-2.amazonaws.com/k.png"
+        );
+        // Path-style for a custom endpoint, trailing slash trimmed.
+        assert_eq!(
+            object_url(
+                &Some("http://localhost:9000/".to_string()),
+                "us-east-1",
+                "mybucket",
+                "k.png"
+            ),
+            "http://localhost:9000/mybucket/k.png"
+        );
     }
 }